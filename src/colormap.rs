@@ -0,0 +1,57 @@
+//! Small colormaps for mapping a normalized dBFS value onto an RGB color for
+//! the waterfall display, without pulling in a plotting-colormap crate.
+
+/// Selectable colormap for the waterfall.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Colormap {
+    #[default]
+    Viridis,
+    Inferno,
+}
+
+/// Control points sampled from the real viridis/inferno colormaps, coarse
+/// enough to keep this dependency-free while still looking right.
+const VIRIDIS: [[u8; 3]; 5] = [
+    [68, 1, 84],
+    [59, 82, 139],
+    [33, 145, 140],
+    [94, 201, 98],
+    [253, 231, 37],
+];
+const INFERNO: [[u8; 3]; 5] = [
+    [0, 0, 4],
+    [87, 16, 110],
+    [188, 55, 84],
+    [249, 142, 9],
+    [252, 255, 164],
+];
+
+impl Colormap {
+    /// Human readable label for the colormap picker.
+    pub fn label(self) -> &'static str {
+        match self {
+            Colormap::Viridis => "Viridis",
+            Colormap::Inferno => "Inferno",
+        }
+    }
+
+    /// Map `t` (clamped to `0..=1`) to an RGB color via piecewise-linear
+    /// interpolation between this colormap's control points.
+    pub fn sample(self, t: f32) -> [u8; 3] {
+        let stops = match self {
+            Colormap::Viridis => &VIRIDIS,
+            Colormap::Inferno => &INFERNO,
+        };
+        let t = t.clamp(0., 1.);
+        let scaled = t * (stops.len() - 1) as f32;
+        let i = (scaled.floor() as usize).min(stops.len() - 2);
+        let frac = scaled - i as f32;
+        let mut rgb = [0u8; 3];
+        for c in 0..3 {
+            let a = stops[i][c] as f32;
+            let b = stops[i + 1][c] as f32;
+            rgb[c] = (a + (b - a) * frac).round() as u8;
+        }
+        rgb
+    }
+}
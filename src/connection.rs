@@ -0,0 +1,186 @@
+//! Capture source lifecycle: the GUI thread sends [`Command`]s over a
+//! channel to pick a serial port or audio device and start or stop
+//! streaming, and this module's [`run`] loop owns the current
+//! [`SampleSource`], retrying with backoff instead of panicking when it
+//! drops or sends a malformed block.
+
+use num_complex::Complex32;
+use std::sync::mpsc::{Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::cpal_source::CpalSource;
+use crate::source::{Protocol, SampleSource, SerialSource, SourceError};
+use crate::window::Window;
+use crate::N;
+
+/// Commands the GUI thread sends to the comms thread.
+pub enum Command {
+    /// Open the serial ADC at `port_name`/`baud`, speaking `protocol`, and
+    /// start streaming.
+    ConnectSerial {
+        port_name: String,
+        baud: u32,
+        protocol: Protocol,
+    },
+    /// Open the named audio input device and start streaming.
+    ConnectCpal { device_name: String },
+    /// Close whatever source is currently open.
+    Disconnect,
+}
+
+/// One FFT frame handed to the GUI thread, tagged with the sample rate and
+/// 0 dBFS reference it was captured with so the plot's frequency axis and
+/// dBFS scaling stay correct when the source changes.
+pub struct Frame {
+    pub fs: f64,
+    pub full_scale: f32,
+    pub bins: Vec<Complex32>,
+}
+
+/// The comms supervisor: waits for a connect command, then pulls blocks
+/// from the resulting [`SampleSource`], FFTs them, and sends the result to
+/// the GUI until it errors out or a `Command::Disconnect` arrives, retrying
+/// with exponential backoff on error.
+pub fn run(
+    commands: Receiver<Command>,
+    data: Sender<Frame>,
+    status: Sender<String>,
+    window: Arc<Mutex<Window>>,
+) {
+    let mut target: Option<Command> = None;
+    let mut backoff = Duration::from_millis(250);
+
+    loop {
+        if target.is_none() {
+            match commands.recv() {
+                Ok(cmd @ (Command::ConnectSerial { .. } | Command::ConnectCpal { .. })) => {
+                    target = Some(cmd)
+                }
+                Ok(Command::Disconnect) | Err(_) => continue,
+            }
+        }
+
+        let (label, opened) = match target.as_ref().unwrap() {
+            Command::ConnectSerial {
+                port_name,
+                baud,
+                protocol,
+            } => (
+                port_name.clone(),
+                SerialSource::open(port_name, *baud, *protocol)
+                    .map(|s| Box::new(s) as Box<dyn SampleSource>),
+            ),
+            Command::ConnectCpal { device_name } => (
+                device_name.clone(),
+                CpalSource::open(device_name).map(|s| Box::new(s) as Box<dyn SampleSource>),
+            ),
+            Command::Disconnect => unreachable!(),
+        };
+
+        match opened {
+            Ok(mut source) => {
+                let _ = status.send(format!("Connected to {label}"));
+                backoff = Duration::from_millis(250);
+                match drive(&mut *source, &data, &window, &commands) {
+                    Ok(DriveExit::Disconnected) => {
+                        target = None;
+                        let _ = status.send("Disconnected".to_string());
+                    }
+                    Ok(DriveExit::Switch(cmd)) => {
+                        // A Connect arrived while we were already streaming;
+                        // re-target instead of dropping it on the floor.
+                        target = Some(cmd);
+                    }
+                    Err(e) => {
+                        let _ = status.send(format!("{label}: {e}, reconnecting..."));
+                        std::thread::sleep(backoff);
+                        backoff = (backoff * 2).min(Duration::from_secs(5));
+                    }
+                }
+            }
+            Err(e) => {
+                let _ = status.send(format!("Failed to open {label}: {e}, retrying..."));
+                std::thread::sleep(backoff);
+                backoff = (backoff * 2).min(Duration::from_secs(5));
+            }
+        }
+
+        // Pick up whatever command arrived while we were connected or
+        // backing off, so a Disconnect/Connect during a retry isn't lost.
+        while let Ok(cmd) = commands.try_recv() {
+            match cmd {
+                Command::Disconnect => target = None,
+                connect => target = Some(connect),
+            }
+        }
+    }
+}
+
+/// Why [`drive`] stopped pulling frames from the source.
+enum DriveExit {
+    /// `Command::Disconnect` arrived; go idle until the next connect.
+    Disconnected,
+    /// A `ConnectSerial`/`ConnectCpal` arrived while already connected;
+    /// `run` should close this source and open the new one instead of
+    /// discarding the command.
+    Switch(Command),
+}
+
+/// Pull blocks from `source`, window and FFT them, and send the result to
+/// the GUI until `commands` delivers a connect/disconnect command (returning
+/// the matching `DriveExit`) or the source errors out (returning `Err`,
+/// which tells `run` to reopen it and try again).
+fn drive(
+    source: &mut dyn SampleSource,
+    channel: &Sender<Frame>,
+    window: &Arc<Mutex<Window>>,
+    commands: &Receiver<Command>,
+) -> Result<DriveExit, SourceError> {
+    // Precompute the coefficient table and coherent gain for every
+    // selectable window once, up front, so changing windows at runtime
+    // doesn't cost a recompute on the hot path.
+    let tables: Vec<(Window, Vec<f32>, f32)> = Window::ALL
+        .iter()
+        .map(|w| {
+            let (coeffs, gain) = w.coefficients(N);
+            (*w, coeffs, gain)
+        })
+        .collect();
+
+    loop {
+        match commands.try_recv() {
+            Ok(Command::Disconnect) => return Ok(DriveExit::Disconnected),
+            Ok(cmd) => return Ok(DriveExit::Switch(cmd)),
+            Err(_) => {}
+        }
+
+        let samples = source.next_block()?;
+
+        let (_, coeffs, gain) = tables
+            .iter()
+            .find(|(w, _, _)| *w == *window.lock().unwrap())
+            .unwrap();
+        let gain = *gain;
+        let mut fftbuf = [0f32; N];
+        for i in 0..fftbuf.len() {
+            fftbuf[i] = samples[i] * coeffs[i];
+        }
+        let mut v = microfft::real::rfft_8192(&mut fftbuf);
+
+        // Undo the window's coherent gain so a full-scale sine still reads
+        // 0 dBFS regardless of which window is selected
+        for bin in v.iter_mut() {
+            *bin /= gain;
+        }
+
+        let frame = Frame {
+            fs: source.sample_rate(),
+            full_scale: source.full_scale(),
+            bins: Vec::from(v),
+        };
+        if channel.send(frame).is_err() {
+            return Ok(DriveExit::Disconnected);
+        }
+    }
+}
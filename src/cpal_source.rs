@@ -0,0 +1,144 @@
+//! A [`SampleSource`] backed by a sound card input device via `cpal`, so the
+//! viewer can double as a general audio spectrum analyzer instead of only
+//! talking to the serial ADC.
+
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use cpal::{FromSample, Sample, SizedSample};
+use std::sync::mpsc::{channel, Receiver, Sender};
+
+use crate::source::{SampleSource, SourceError};
+use crate::N;
+
+/// Captures audio from an input device chosen by name and hands back fixed
+/// size blocks of `N` mean-subtracted samples.
+pub struct CpalSource {
+    // Kept alive for the lifetime of the source; dropping it stops capture.
+    _stream: cpal::Stream,
+    samples: Receiver<f32>,
+    sample_rate: f64,
+    pending: Vec<f32>,
+}
+
+impl CpalSource {
+    /// List the names of every available audio input device.
+    pub fn available_devices() -> Vec<String> {
+        let Ok(devices) = cpal::default_host().input_devices() else {
+            return Vec::new();
+        };
+        devices.filter_map(|d| d.name().ok()).collect()
+    }
+
+    /// Open the input device named `device_name` at its default config and
+    /// start streaming.
+    pub fn open(device_name: &str) -> Result<Self, SourceError> {
+        let host = cpal::default_host();
+        let device = host
+            .input_devices()
+            .map_err(|e| SourceError::Cpal(e.to_string()))?
+            .find(|d| d.name().map(|n| n == device_name).unwrap_or(false))
+            .ok_or_else(|| SourceError::Cpal(format!("no such input device: {device_name}")))?;
+
+        let config = device
+            .default_input_config()
+            .map_err(|e| SourceError::Cpal(e.to_string()))?;
+        let sample_rate = config.sample_rate().0 as f64;
+        let channels = config.channels() as usize;
+        let sample_format = config.sample_format();
+        let stream_config = config.into();
+
+        let (tx, rx) = channel();
+        let err_tx = tx.clone();
+        // The device's default format isn't always f32 (ALSA/WASAPI often
+        // default to i16 or u16); build the matching stream type and
+        // convert every sample to f32 rather than assuming f32 and either
+        // failing to build the stream or misinterpreting the bytes.
+        let stream = match sample_format {
+            cpal::SampleFormat::F32 => {
+                build_input_stream::<f32>(&device, &stream_config, channels, tx, err_tx)
+            }
+            cpal::SampleFormat::I16 => {
+                build_input_stream::<i16>(&device, &stream_config, channels, tx, err_tx)
+            }
+            cpal::SampleFormat::U16 => {
+                build_input_stream::<u16>(&device, &stream_config, channels, tx, err_tx)
+            }
+            other => Err(SourceError::Cpal(format!(
+                "unsupported sample format: {other:?}"
+            ))),
+        }?;
+        stream
+            .play()
+            .map_err(|e| SourceError::Cpal(e.to_string()))?;
+
+        Ok(CpalSource {
+            _stream: stream,
+            samples: rx,
+            sample_rate,
+            pending: Vec::with_capacity(N),
+        })
+    }
+}
+
+/// Build an input stream over samples of type `T`, downmixing to mono and
+/// converting every sample to `f32` before sending it on `tx`.
+fn build_input_stream<T>(
+    device: &cpal::Device,
+    config: &cpal::StreamConfig,
+    channels: usize,
+    tx: Sender<f32>,
+    err_tx: Sender<f32>,
+) -> Result<cpal::Stream, SourceError>
+where
+    T: SizedSample,
+    f32: FromSample<T>,
+{
+    device
+        .build_input_stream(
+            config,
+            move |data: &[T], _: &cpal::InputCallbackInfo| {
+                // Downmix to mono by taking every `channels`-th sample;
+                // good enough for a spectrum analyzer.
+                for frame in data.chunks(channels) {
+                    let _ = tx.send(frame[0].to_sample::<f32>());
+                }
+            },
+            move |e| {
+                // The stream error callback has no Result to propagate;
+                // best effort is to surface it on the sample channel so
+                // `next_block` fails and the supervisor reconnects.
+                drop(err_tx.send(f32::NAN));
+                eprintln!("cpal input stream error: {e}");
+            },
+            None,
+        )
+        .map_err(|e| SourceError::Cpal(e.to_string()))
+}
+
+impl SampleSource for CpalSource {
+    fn sample_rate(&self) -> f64 {
+        self.sample_rate
+    }
+
+    fn full_scale(&self) -> f32 {
+        // cpal delivers samples normalized to +/-1.0 regardless of the
+        // device's native format, unlike the serial ADC's raw counts.
+        1.0
+    }
+
+    fn next_block(&mut self) -> Result<Vec<f32>, SourceError> {
+        while self.pending.len() < N {
+            let sample = self
+                .samples
+                .recv()
+                .map_err(|_| SourceError::Cpal("input stream closed".into()))?;
+            if sample.is_nan() {
+                return Err(SourceError::Cpal("input stream error".into()));
+            }
+            self.pending.push(sample);
+        }
+
+        let block = std::mem::replace(&mut self.pending, Vec::with_capacity(N));
+        let mean = block.iter().sum::<f32>() / N as f32;
+        Ok(block.into_iter().map(|s| s - mean).collect())
+    }
+}
@@ -0,0 +1,33 @@
+//! Display modes controlling how successive FFT frames are combined before
+//! being shown, so transients aren't lost and weak tones are easier to read.
+
+/// How incoming per-bin magnitudes are combined into the displayed spectrum.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum DisplayMode {
+    /// Show each incoming frame as-is, with no history.
+    #[default]
+    Instantaneous,
+    /// Exponential moving average of magnitudes: `avg = a*new + (1-a)*avg`.
+    Average,
+    /// Running per-bin maximum, with an optional multiplicative decay applied
+    /// each frame so old peaks eventually fall back out.
+    PeakHold,
+}
+
+impl DisplayMode {
+    /// All selectable variants, in the order they should appear in the GUI.
+    pub const ALL: [DisplayMode; 3] = [
+        DisplayMode::Instantaneous,
+        DisplayMode::Average,
+        DisplayMode::PeakHold,
+    ];
+
+    /// Human readable label for the display-mode picker.
+    pub fn label(self) -> &'static str {
+        match self {
+            DisplayMode::Instantaneous => "Instantaneous",
+            DisplayMode::Average => "Average",
+            DisplayMode::PeakHold => "Peak Hold",
+        }
+    }
+}
@@ -1,10 +1,40 @@
-use egui_plot::{Line, Plot, PlotBounds, PlotPoints};
-use num_complex::Complex32;
-use serialport::SerialPort;
+use egui_plot::{Line, MarkerShape, Plot, PlotBounds, PlotPoints, Points, Text};
 
-use std::io::{BufRead, BufReader};
-use std::sync::mpsc::{Receiver, Sender, channel};
-use std::time::Duration;
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+
+mod colormap;
+mod connection;
+mod cpal_source;
+mod display;
+mod peaks;
+mod source;
+mod waterfall;
+mod window;
+use colormap::Colormap;
+use connection::{Command, Frame};
+use cpal_source::CpalSource;
+use display::DisplayMode;
+use peaks::find_peaks;
+use waterfall::Waterfall;
+use window::Window;
+
+/// Which kind of device the connection panel is currently configured to
+/// open.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+enum SourceKind {
+    #[default]
+    Serial,
+    Cpal,
+}
+
+/// Which visualization the central pane currently shows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+enum ViewMode {
+    #[default]
+    Line,
+    Waterfall,
+}
 
 /// The structure of this GUI application is built around egui
 /// which is a wonderfully easy to use immediate mode GUI framework
@@ -17,21 +47,102 @@ use std::time::Duration;
 pub struct SpecViewer {
     /// channel provides a way for the render thread to recieve new data from
     /// the comms thread.
-    channel: Receiver<Vec<Complex32>>,
-    /// A place to stash old data until new data is ready
-    data: Vec<Complex32>,
+    channel: Receiver<Frame>,
+    /// Per-bin linear magnitude actually displayed, combined from incoming
+    /// frames according to `display_mode`.
+    accumulator: Vec<f32>,
+    /// Sample rate of the most recently received frame; determines the
+    /// plot's frequency axis since it depends on which source is open.
+    fs: f64,
+    /// 0 dBFS reference (peak full-scale amplitude) of the most recently
+    /// received frame; differs between the serial ADC and normalized cpal
+    /// audio, so the dBFS scaling stays correct when the source changes.
+    full_scale: f32,
+    /// The window function applied by the comms thread before the FFT,
+    /// shared so the GUI can change it on the fly.
+    window: Arc<Mutex<Window>>,
+    /// Minimum amplitude, in dBFS, for a local maximum to count as a peak.
+    peak_threshold_db: f32,
+    /// How many of the strongest peaks to mark and list.
+    max_peaks: usize,
+    /// How successive frames are combined into `accumulator`.
+    display_mode: DisplayMode,
+    /// Smoothing factor `a` used by `DisplayMode::Average`.
+    avg_alpha: f32,
+    /// Per-frame multiplicative decay used by `DisplayMode::PeakHold`.
+    peak_decay: f32,
+    /// Sends connect/disconnect requests to the comms thread.
+    commands: Sender<Command>,
+    /// Delivers connection status updates from the comms thread.
+    status_channel: Receiver<String>,
+    /// Most recent status message, shown in the connection panel.
+    status: String,
+    /// Which kind of device the connection panel is configured to open.
+    source_kind: SourceKind,
+    /// Ports found by the last `available_ports()` refresh.
+    available_ports: Vec<String>,
+    /// Port currently selected in the connection panel.
+    selected_port: String,
+    /// Baud rate currently selected in the connection panel.
+    selected_baud: u32,
+    /// Wire protocol currently selected in the connection panel.
+    selected_protocol: source::Protocol,
+    /// Audio input devices found by the last refresh.
+    available_cpal_devices: Vec<String>,
+    /// Audio input device currently selected in the connection panel.
+    selected_cpal_device: String,
+    /// Which visualization the central pane currently shows.
+    view: ViewMode,
+    /// Ring of recent frames backing the waterfall view.
+    waterfall: Waterfall,
+    /// Colormap used to render the waterfall.
+    colormap: Colormap,
+    /// Texture the waterfall is rendered into; updated in place each frame.
+    waterfall_texture: Option<egui::TextureHandle>,
 }
 
-/// Define the sample rate
-const FS: f64 = 96000.;
 /// Define our FFT Size
 const N: usize = 8192;
 
 impl eframe::App for SpecViewer {
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
-        // Check for a data update from the FFT+Comms thread
-        if let Ok(d) = self.channel.try_recv() {
-            self.data = d;
+        // Check for a data update from the FFT+Comms thread and fold it
+        // into the accumulator according to the selected display mode
+        if let Ok(frame) = self.channel.try_recv() {
+            self.fs = frame.fs;
+            self.full_scale = frame.full_scale;
+            let mags: Vec<f32> = frame.bins.iter().map(|p| p.norm()).collect();
+            if self.accumulator.len() != mags.len() {
+                self.accumulator = mags;
+            } else {
+                match self.display_mode {
+                    DisplayMode::Instantaneous => self.accumulator = mags,
+                    DisplayMode::Average => {
+                        for (acc, new) in self.accumulator.iter_mut().zip(mags.iter()) {
+                            *acc = self.avg_alpha * new + (1. - self.avg_alpha) * *acc;
+                        }
+                    }
+                    DisplayMode::PeakHold => {
+                        for (acc, new) in self.accumulator.iter_mut().zip(mags.iter()) {
+                            *acc = new.max(*acc * self.peak_decay);
+                        }
+                    }
+                }
+            }
+
+            // Feed the same dBFS values shown on the line plot into the
+            // waterfall as its newest row
+            let db_row: Vec<f32> = self
+                .accumulator
+                .iter()
+                .map(|mag| 10. * (mag / (self.full_scale * N as f32)).log10())
+                .collect();
+            self.waterfall.push(db_row);
+        }
+
+        // Drain the status channel, keeping only the latest message
+        while let Ok(status) = self.status_channel.try_recv() {
+            self.status = status;
         }
 
         // Boilerplate File Quit and Light/Dark theme pane
@@ -45,108 +156,316 @@ impl eframe::App for SpecViewer {
                 ui.add_space(16.0);
 
                 egui::widgets::global_theme_preference_buttons(ui);
+                ui.add_space(16.0);
+
+                // Source picker: lets the user choose between the serial
+                // ADC and a sound-card input device, instead of a
+                // hardcoded serial path.
+                egui::ComboBox::from_label("Source")
+                    .selected_text(match self.source_kind {
+                        SourceKind::Serial => "Serial ADC",
+                        SourceKind::Cpal => "Audio Input",
+                    })
+                    .show_ui(ui, |ui| {
+                        ui.selectable_value(
+                            &mut self.source_kind,
+                            SourceKind::Serial,
+                            "Serial ADC",
+                        );
+                        ui.selectable_value(&mut self.source_kind, SourceKind::Cpal, "Audio Input");
+                    });
+
+                match self.source_kind {
+                    SourceKind::Serial => {
+                        if ui.button("Refresh ports").clicked() {
+                            self.available_ports = serialport::available_ports()
+                                .unwrap_or_default()
+                                .into_iter()
+                                .map(|p| p.port_name)
+                                .collect();
+                        }
+                        egui::ComboBox::from_label("Port")
+                            .selected_text(if self.selected_port.is_empty() {
+                                "<select>"
+                            } else {
+                                &self.selected_port
+                            })
+                            .show_ui(ui, |ui| {
+                                for port in &self.available_ports {
+                                    ui.selectable_value(
+                                        &mut self.selected_port,
+                                        port.clone(),
+                                        port,
+                                    );
+                                }
+                            });
+                        egui::ComboBox::from_label("Baud")
+                            .selected_text(self.selected_baud.to_string())
+                            .show_ui(ui, |ui| {
+                                for baud in [9600, 115200, 230400, 460800, 921600, 115200 * 32] {
+                                    ui.selectable_value(
+                                        &mut self.selected_baud,
+                                        baud,
+                                        baud.to_string(),
+                                    );
+                                }
+                            });
+                        egui::ComboBox::from_label("Protocol")
+                            .selected_text(self.selected_protocol.label())
+                            .show_ui(ui, |ui| {
+                                ui.selectable_value(
+                                    &mut self.selected_protocol,
+                                    source::Protocol::Text,
+                                    source::Protocol::Text.label(),
+                                );
+                                ui.selectable_value(
+                                    &mut self.selected_protocol,
+                                    source::Protocol::Cobs,
+                                    source::Protocol::Cobs.label(),
+                                );
+                            });
+                        if ui
+                            .add_enabled(
+                                !self.selected_port.is_empty(),
+                                egui::Button::new("Connect"),
+                            )
+                            .clicked()
+                        {
+                            let _ = self.commands.send(Command::ConnectSerial {
+                                port_name: self.selected_port.clone(),
+                                baud: self.selected_baud,
+                                protocol: self.selected_protocol,
+                            });
+                        }
+                    }
+                    SourceKind::Cpal => {
+                        if ui.button("Refresh devices").clicked() {
+                            self.available_cpal_devices = CpalSource::available_devices();
+                        }
+                        egui::ComboBox::from_label("Device")
+                            .selected_text(if self.selected_cpal_device.is_empty() {
+                                "<select>"
+                            } else {
+                                &self.selected_cpal_device
+                            })
+                            .show_ui(ui, |ui| {
+                                for device in &self.available_cpal_devices {
+                                    ui.selectable_value(
+                                        &mut self.selected_cpal_device,
+                                        device.clone(),
+                                        device,
+                                    );
+                                }
+                            });
+                        if ui
+                            .add_enabled(
+                                !self.selected_cpal_device.is_empty(),
+                                egui::Button::new("Connect"),
+                            )
+                            .clicked()
+                        {
+                            let _ = self.commands.send(Command::ConnectCpal {
+                                device_name: self.selected_cpal_device.clone(),
+                            });
+                        }
+                    }
+                }
+                if ui.button("Disconnect").clicked() {
+                    let _ = self.commands.send(Command::Disconnect);
+                }
+                ui.add_space(16.0);
+
+                // Let the user pick the window function applied before the
+                // FFT; the reader thread picks up the change on its next
+                // iteration.
+                let mut window = self.window.lock().unwrap();
+                egui::ComboBox::from_label("Window")
+                    .selected_text(window.label())
+                    .show_ui(ui, |ui| {
+                        for w in Window::ALL {
+                            ui.selectable_value(&mut *window, w, w.label());
+                        }
+                    });
+                drop(window);
+                ui.add_space(16.0);
+
+                // Let the user pick how successive frames are combined
+                // before display, and tune the mode's one parameter.
+                egui::ComboBox::from_label("Display")
+                    .selected_text(self.display_mode.label())
+                    .show_ui(ui, |ui| {
+                        for m in DisplayMode::ALL {
+                            ui.selectable_value(&mut self.display_mode, m, m.label());
+                        }
+                    });
+                match self.display_mode {
+                    DisplayMode::Average => {
+                        ui.add(egui::Slider::new(&mut self.avg_alpha, 0.01..=1.0).text("a"));
+                    }
+                    DisplayMode::PeakHold => {
+                        ui.add(egui::Slider::new(&mut self.peak_decay, 0.9..=1.0).text("Decay"));
+                    }
+                    DisplayMode::Instantaneous => {}
+                }
+                ui.add_space(16.0);
+
+                // Toggle between the line spectrum and the waterfall, and
+                // pick the waterfall's colormap when it's showing.
+                egui::ComboBox::from_label("View")
+                    .selected_text(match self.view {
+                        ViewMode::Line => "Line",
+                        ViewMode::Waterfall => "Waterfall",
+                    })
+                    .show_ui(ui, |ui| {
+                        ui.selectable_value(&mut self.view, ViewMode::Line, "Line");
+                        ui.selectable_value(&mut self.view, ViewMode::Waterfall, "Waterfall");
+                    });
+                if self.view == ViewMode::Waterfall {
+                    egui::ComboBox::from_label("Colormap")
+                        .selected_text(self.colormap.label())
+                        .show_ui(ui, |ui| {
+                            ui.selectable_value(
+                                &mut self.colormap,
+                                Colormap::Viridis,
+                                Colormap::Viridis.label(),
+                            );
+                            ui.selectable_value(
+                                &mut self.colormap,
+                                Colormap::Inferno,
+                                Colormap::Inferno.label(),
+                            );
+                        });
+                }
             });
         });
 
-        // Central Pane with all our plots
-        egui::CentralPanel::default().show(ctx, |ui| {
-            // Rescale our FFT from complex amplitude to dBFS, such that
-            // 0 dBFS is a full scale sine wave. We intentionally do not scale
-            // by frequency to get a PSD because we do not know the precisce
-            // frequency response of the microphone so it would be meaningless.
-            let points: PlotPoints = self
-                .data
-                .iter()
-                .enumerate()
-                .map(|(i, p)| {
-                    [
-                        (i as f64) * FS / (N as f64),
-                        10. * (p / (2048. * (N as f32))).norm().log10() as f64,
-                    ]
-                })
-                .collect();
+        // Status bar showing the comms thread's current connection state
+        egui::TopBottomPanel::bottom("status_bar").show(ctx, |ui| {
+            ui.label(&self.status);
+        });
 
-            // Create and format our plot
-            let line = Line::new(points);
-            Plot::new("Data")
-                .allow_zoom([true, false])
-                .auto_bounds([true, false])
-                .x_axis_label("Frequency (Hz)")
-                .y_axis_label("Power (dBFS)")
-                .show(ui, |plot_ui| {
-                    // Have plot bounds adjust in a way that is reminiscint of
-                    // most spectrum analyzers, and makes sense for the input
-                    // data range.
-                    plot_ui.line(line);
-                    let bounds = plot_ui.plot_bounds();
-                    if *bounds.range_x().start() < 0.
-                        || *bounds.range_x().end() > (FS / 2.)
-                        || *bounds.range_y().end() > 0.
-                        || *bounds.range_y().start() < -60.
-                    {
-                        let bounds = PlotBounds::from_min_max(
-                            [bounds.range_x().start().max(0.), -60.],
-                            [bounds.range_x().end().min(FS / 2.), 0.],
-                        );
-                        plot_ui.set_plot_bounds(bounds);
-                    }
-                });
+        // Find the strongest peaks in this frame so we can both mark them
+        // on the plot and list them in the side panel below.
+        let peaks = find_peaks(
+            &self.accumulator,
+            self.fs,
+            N,
+            self.full_scale as f64,
+            self.peak_threshold_db as f64,
+            self.max_peaks,
+        );
+
+        // Side panel with the peak threshold/count controls and a table of
+        // the peaks found this frame.
+        egui::SidePanel::right("peaks_panel").show(ctx, |ui| {
+            ui.heading("Peaks");
+            ui.add(
+                egui::Slider::new(&mut self.peak_threshold_db, -60.0..=0.0)
+                    .text("Threshold (dBFS)"),
+            );
+            ui.add(
+                egui::DragValue::new(&mut self.max_peaks)
+                    .range(1..=20)
+                    .prefix("Count: "),
+            );
+            ui.separator();
+            egui::Grid::new("peaks_table").striped(true).show(ui, |ui| {
+                ui.label("Freq");
+                ui.label("Amp");
+                ui.end_row();
+                for peak in &peaks {
+                    ui.label(if peak.freq_hz >= 1000. {
+                        format!("{:.3} kHz", peak.freq_hz / 1000.)
+                    } else {
+                        format!("{:.1} Hz", peak.freq_hz)
+                    });
+                    ui.label(format!("{:.1} dBFS", peak.db));
+                    ui.end_row();
+                }
+            });
         });
-        ctx.request_repaint();
-    }
-}
 
-/// This function runs a loop that reads from the ADC and performs an FFT
-fn reader(mut port: Box<dyn SerialPort>, channel: Sender<Vec<Complex32>>) {
-    // There's no no timeout option, so set it very high
-    port.set_timeout(Duration::from_secs(8192)).unwrap();
-    // Clear anything already recieved by the OS
-    port.clear(serialport::ClearBuffer::All).unwrap();
-    // Send the p comand, which forms the Computer->Device framing boundary
-    port.write_all(b"p").unwrap();
-
-    // Alocate a resizable buffer for our bufreader on the heap;
-    let mut buf = Vec::new();
-    // Allocate a resizable buffer for the data
-    let mut d: Vec<u16> = vec![];
-
-    // Main recieve/fft loop
-    loop {
-        // Recieve from the device until we get a \xff which forms the
-        // Device->Computer framing boundary
-        let mut bufreader = BufReader::new(port);
-        bufreader.read_until(0xff, &mut buf).unwrap();
-
-        // Turn our bufreader back into a serial port and request a new
-        // buffer of ADC data while we process this one
-        port = bufreader.into_inner();
-        port.write_all(b"p").unwrap();
-        // Pop the \xff out of our data as it isn't part of the text we parse
-        buf.pop().unwrap();
-
-        // Parse the string data recieved from the device, crashing if it is
-        // malformed
-        let string = String::from_utf8(buf.clone()).unwrap();
-        {
-            for line in string.lines() {
-                d.push(line.parse().unwrap_or_else(|_e| panic!("{}", line)));
-            }
+        // Central Pane with all our plots
+        egui::CentralPanel::default().show(ctx, |ui| match self.view {
+            ViewMode::Line => {
+                // Rescale our FFT from complex amplitude to dBFS, such that
+                // 0 dBFS is a full scale sine wave. We intentionally do not
+                // scale by frequency to get a PSD because we do not know
+                // the precisce frequency response of the microphone so it
+                // would be meaningless.
+                let points: PlotPoints = self
+                    .accumulator
+                    .iter()
+                    .enumerate()
+                    .map(|(i, mag)| {
+                        [
+                            (i as f64) * self.fs / (N as f64),
+                            10. * (*mag as f64 / (self.full_scale as f64 * N as f64)).log10(),
+                        ]
+                    })
+                    .collect();
 
-            // Subtract out the zero Hz bin (as the ADC input is single ended
-            // and biased to almost but not quite VCC/2), and then perform
-            // the FFT
-            let mean = d.iter().map(|i| *i as usize).sum::<usize>() as f32 / d.len() as f32;
-            let mut fftbuf = [0f32; N];
-            for i in 0..fftbuf.len() {
-                fftbuf[i] = d[i] as f32 - mean;
-            }
-            let v = microfft::real::rfft_8192(&mut fftbuf);
+                // Markers and labels for the peaks found above
+                let marker_points: PlotPoints = peaks.iter().map(|p| [p.freq_hz, p.db]).collect();
+                let markers = Points::new(marker_points)
+                    .shape(MarkerShape::Diamond)
+                    .radius(4.);
 
-            // Send the data off to our render thread
-            channel.send(Vec::from(v)).unwrap();
-            d = Vec::with_capacity(N);
-        }
-        buf.clear();
+                // Create and format our plot
+                let line = Line::new(points);
+                Plot::new("Data")
+                    .allow_zoom([true, false])
+                    .auto_bounds([true, false])
+                    .x_axis_label("Frequency (Hz)")
+                    .y_axis_label("Power (dBFS)")
+                    .show(ui, |plot_ui| {
+                        // Have plot bounds adjust in a way that is
+                        // reminiscint of most spectrum analyzers, and makes
+                        // sense for the input data range.
+                        plot_ui.line(line);
+                        plot_ui.points(markers);
+                        for peak in &peaks {
+                            let label = if peak.freq_hz >= 1000. {
+                                format!("{:.3} kHz\n{:.1} dBFS", peak.freq_hz / 1000., peak.db)
+                            } else {
+                                format!("{:.1} Hz\n{:.1} dBFS", peak.freq_hz, peak.db)
+                            };
+                            plot_ui.text(Text::new(
+                                egui_plot::PlotPoint::new(peak.freq_hz, peak.db),
+                                label,
+                            ));
+                        }
+                        let bounds = plot_ui.plot_bounds();
+                        if *bounds.range_x().start() < 0.
+                            || *bounds.range_x().end() > (self.fs / 2.)
+                            || *bounds.range_y().end() > 0.
+                            || *bounds.range_y().start() < -60.
+                        {
+                            let bounds = PlotBounds::from_min_max(
+                                [bounds.range_x().start().max(0.), -60.],
+                                [bounds.range_x().end().min(self.fs / 2.), 0.],
+                            );
+                            plot_ui.set_plot_bounds(bounds);
+                        }
+                    });
+            }
+            ViewMode::Waterfall => {
+                // Same -60..0 dBFS clamp as the line plot, mapped through
+                // the selected colormap.
+                let image = self.waterfall.to_color_image(self.colormap, -60., 0.);
+                let texture = self.waterfall_texture.get_or_insert_with(|| {
+                    ui.ctx()
+                        .load_texture("waterfall", image.clone(), Default::default())
+                });
+                texture.set(image, Default::default());
+                ui.add(
+                    egui::Image::new((texture.id(), ui.available_size()))
+                        .fit_to_exact_size(egui::vec2(ui.available_width(), ui.available_height()))
+                        .maintain_aspect_ratio(false),
+                );
+            }
+        });
+        ctx.request_repaint();
     }
 }
 
@@ -163,13 +482,20 @@ fn main() -> eframe::Result {
     // can chat through.
     let (sender, reciever) = channel();
 
-    // Open the "serial" port to the device and launch the COMs
-    // thread. This is actually a pure USB serial device on both ends
-    // , so the baud rate can be quite high and is fairly arbitrary
-    let port = "/dev/cu.usbmodemSPECT1";
-    let port = serialport::new(port, 115200 * 32).open().unwrap();
+    // Commands flow GUI -> comms thread (connect/disconnect), status
+    // messages flow comms thread -> GUI.
+    let (command_tx, command_rx) = channel();
+    let (status_tx, status_rx) = channel();
+
+    // Shared window selection; the GUI thread writes to it, the reader
+    // thread reads it once per frame.
+    let window = Arc::new(Mutex::new(Window::default()));
+    let reader_window = window.clone();
+
+    // Launch the comms thread. It sits idle until the GUI sends a connect
+    // command, then owns the source for as long as it stays open.
     std::thread::spawn(move || {
-        reader(port, sender);
+        connection::run(command_rx, sender, status_tx, reader_window);
     });
 
     // Start rendering on this thread
@@ -178,8 +504,30 @@ fn main() -> eframe::Result {
         native_options,
         Box::new(|_cc| {
             Ok(Box::new(SpecViewer {
-                data: vec![],
+                accumulator: vec![],
                 channel: reciever,
+                fs: source::SERIAL_FS,
+                full_scale: source::SERIAL_FULL_SCALE,
+                window,
+                peak_threshold_db: -40.,
+                max_peaks: 5,
+                display_mode: DisplayMode::default(),
+                avg_alpha: 0.3,
+                peak_decay: 0.995,
+                commands: command_tx,
+                status_channel: status_rx,
+                status: "Not connected".to_string(),
+                source_kind: SourceKind::default(),
+                available_ports: Vec::new(),
+                selected_port: String::new(),
+                selected_baud: 115200 * 32,
+                selected_protocol: source::Protocol::default(),
+                available_cpal_devices: Vec::new(),
+                selected_cpal_device: String::new(),
+                view: ViewMode::default(),
+                waterfall: Waterfall::default(),
+                colormap: Colormap::default(),
+                waterfall_texture: None,
             }))
         }),
     )
@@ -0,0 +1,58 @@
+//! Peak detection on the FFT magnitude spectrum, with sub-bin frequency
+//! refinement via quadratic interpolation of the neighboring log-magnitudes.
+
+/// A single detected spectral peak.
+#[derive(Debug, Clone, Copy)]
+pub struct Peak {
+    /// Refined frequency estimate, in Hz.
+    pub freq_hz: f64,
+    /// Refined amplitude estimate, in dBFS.
+    pub db: f64,
+}
+
+/// Scan `mags` (linear per-bin magnitude) for local maxima above
+/// `threshold_db`, returning up to `max_peaks` of them ordered from
+/// strongest to weakest.
+///
+/// Each bin is converted to dBFS using the same scaling as the line plot
+/// (`10 * log10(mag / (full_scale * n))`, where `full_scale` is the
+/// source's peak full-scale amplitude), and peaks found on the bin grid are
+/// refined to sub-bin accuracy: with `alpha`, `beta`, `gamma` the dB values
+/// at bins `i-1, i, i+1`, the fractional bin offset is
+/// `p = 0.5*(alpha - gamma)/(alpha - 2*beta + gamma)`, giving a refined
+/// frequency of `(i + p) * fs / n` and a refined amplitude of
+/// `beta - 0.25*(alpha - gamma)*p`.
+pub fn find_peaks(
+    mags: &[f32],
+    fs: f64,
+    n: usize,
+    full_scale: f64,
+    threshold_db: f64,
+    max_peaks: usize,
+) -> Vec<Peak> {
+    let db: Vec<f64> = mags
+        .iter()
+        .map(|m| 10. * (*m as f64 / (full_scale * n as f64)).log10())
+        .collect();
+
+    let mut peaks: Vec<Peak> = Vec::new();
+    for i in 1..db.len().saturating_sub(1) {
+        let (alpha, beta, gamma) = (db[i - 1], db[i], db[i + 1]);
+        if beta > alpha && beta > gamma && beta > threshold_db {
+            let denom = alpha - 2. * beta + gamma;
+            let p = if denom != 0. {
+                0.5 * (alpha - gamma) / denom
+            } else {
+                0.
+            };
+            peaks.push(Peak {
+                freq_hz: (i as f64 + p) * fs / n as f64,
+                db: beta - 0.25 * (alpha - gamma) * p,
+            });
+        }
+    }
+
+    peaks.sort_by(|a, b| b.db.total_cmp(&a.db));
+    peaks.truncate(max_peaks);
+    peaks
+}
@@ -0,0 +1,231 @@
+//! The [`SampleSource`] trait abstracts over where ADC/audio samples come
+//! from, so the FFT pipeline in [`crate::connection`] doesn't care whether
+//! it's reading the serial ADC or a sound card.
+
+use serde::Deserialize;
+use serialport::SerialPort;
+use std::fmt;
+use std::io::{BufRead, BufReader};
+use std::time::Duration;
+
+use crate::N;
+
+/// Sample rate of the serial ADC, which is fixed by the firmware when
+/// speaking [`Protocol::Text`]. [`Protocol::Cobs`] instead carries its own
+/// sample rate per frame, since that's the whole point of upgrading it.
+pub const SERIAL_FS: f64 = 96000.;
+
+/// Half-range of the serial ADC (12-bit, single-ended, mean-subtracted),
+/// i.e. the peak amplitude of a full-scale reading - the 0 dBFS reference
+/// for [`SerialSource`].
+pub const SERIAL_FULL_SCALE: f32 = 2048.;
+
+/// Which on-wire framing the serial ADC is speaking.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Protocol {
+    /// Newline-separated decimal samples terminated by `0xff`. Fragile -
+    /// a single dropped byte desyncs the parser - but kept around for
+    /// firmware that hasn't been updated yet.
+    #[default]
+    Text,
+    /// `postcard`-encoded [`CobsBlock`] messages, COBS-framed with `0x00`
+    /// as the frame delimiter. Self-resynchronizing: a corrupt frame is
+    /// just skipped rather than taking the whole connection down.
+    Cobs,
+}
+
+impl Protocol {
+    /// Human readable label for the protocol picker.
+    pub fn label(self) -> &'static str {
+        match self {
+            Protocol::Text => "Text",
+            Protocol::Cobs => "COBS",
+        }
+    }
+}
+
+/// Payload of a [`Protocol::Cobs`] frame: a block of ADC samples plus the
+/// sample rate they were captured at, so the firmware can change rate
+/// without a protocol version bump.
+#[derive(Deserialize)]
+struct CobsBlock {
+    sample_rate: u32,
+    samples: Vec<u16>,
+}
+
+/// Something that can be asked to block until `N` fresh samples are ready.
+pub trait SampleSource {
+    /// Sample rate of this source, in Hz.
+    fn sample_rate(&self) -> f64;
+    /// Peak linear amplitude of a full-scale input from this source (e.g.
+    /// the ADC's half-range for the serial source, or `1.0` for normalized
+    /// audio samples), used as the 0 dBFS reference when converting
+    /// magnitudes to decibels.
+    fn full_scale(&self) -> f32;
+    /// Block until `N` mean-subtracted samples are available. Returns `Err`
+    /// if the underlying device dropped or sent malformed data.
+    fn next_block(&mut self) -> Result<Vec<f32>, SourceError>;
+}
+
+/// Everything that can go wrong reading from a [`SampleSource`], none of
+/// which should crash the app - the supervisor loop in `connection::run`
+/// reconnects after any of these instead.
+#[derive(Debug)]
+pub enum SourceError {
+    Io(std::io::Error),
+    Serial(serialport::Error),
+    Cpal(String),
+    Malformed(String),
+}
+
+impl fmt::Display for SourceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SourceError::Io(e) => write!(f, "I/O error: {e}"),
+            SourceError::Serial(e) => write!(f, "serial error: {e}"),
+            SourceError::Cpal(msg) => write!(f, "cpal error: {msg}"),
+            SourceError::Malformed(msg) => write!(f, "malformed frame: {msg}"),
+        }
+    }
+}
+
+impl From<std::io::Error> for SourceError {
+    fn from(e: std::io::Error) -> Self {
+        SourceError::Io(e)
+    }
+}
+
+impl From<serialport::Error> for SourceError {
+    fn from(e: serialport::Error) -> Self {
+        SourceError::Serial(e)
+    }
+}
+
+/// Reads ADC samples off the serial port, speaking either the legacy text
+/// protocol or COBS-framed `postcard` messages depending on `protocol`.
+pub struct SerialSource {
+    port: Option<Box<dyn SerialPort>>,
+    buf: Vec<u8>,
+    samples: Vec<u16>,
+    protocol: Protocol,
+    sample_rate: f64,
+}
+
+impl SerialSource {
+    /// Open `port_name` at `baud` and send the initial `p` command that
+    /// forms the computer -> device framing boundary.
+    pub fn open(port_name: &str, baud: u32, protocol: Protocol) -> Result<Self, SourceError> {
+        let mut port = serialport::new(port_name, baud).open()?;
+        // There's no no timeout option, so set it very high
+        port.set_timeout(Duration::from_secs(8192))?;
+        // Clear anything already recieved by the OS
+        port.clear(serialport::ClearBuffer::All)?;
+        port.write_all(b"p")?;
+        Ok(SerialSource {
+            port: Some(port),
+            buf: Vec::new(),
+            samples: Vec::with_capacity(N),
+            protocol,
+            sample_rate: SERIAL_FS,
+        })
+    }
+
+    /// Request and parse one frame of the legacy newline-separated decimal
+    /// protocol, terminated by `0xff`.
+    fn next_block_text(&mut self) -> Result<Vec<f32>, SourceError> {
+        // Recieve from the device until we get a \xff which forms the
+        // Device->Computer framing boundary
+        let mut bufreader = BufReader::new(self.port.take().expect("port taken twice"));
+        bufreader.read_until(0xff, &mut self.buf)?;
+
+        // Turn our bufreader back into a serial port and request a new
+        // buffer of ADC data while we process this one
+        let mut port = bufreader.into_inner();
+        port.write_all(b"p")?;
+        self.port = Some(port);
+
+        // Pop the \xff out of our data as it isn't part of the text we parse
+        self.buf
+            .pop()
+            .ok_or_else(|| SourceError::Malformed("empty frame".into()))?;
+
+        // Parse the string data recieved from the device, reconnecting
+        // instead of crashing if it is malformed
+        let string = String::from_utf8(self.buf.clone())
+            .map_err(|e| SourceError::Malformed(e.to_string()))?;
+        self.buf.clear();
+
+        self.samples.clear();
+        for line in string.lines() {
+            self.samples.push(
+                line.parse()
+                    .map_err(|_| SourceError::Malformed(format!("bad sample {line:?}")))?,
+            );
+        }
+        if self.samples.len() != N {
+            return Err(SourceError::Malformed(format!(
+                "expected {N} samples, got {}",
+                self.samples.len()
+            )));
+        }
+
+        // Subtract out the zero Hz bin, as the ADC input is single ended
+        // and biased to almost but not quite VCC/2
+        let mean = self.samples.iter().map(|i| *i as usize).sum::<usize>() as f32 / N as f32;
+        Ok(self.samples.iter().map(|s| *s as f32 - mean).collect())
+    }
+
+    /// Request and parse one COBS-framed `postcard` message, terminated by
+    /// a zero byte. A frame that fails to decode, fails to parse, or has
+    /// the wrong sample count is dropped rather than treated as a
+    /// connection error: the next `read_until` is already resynchronized
+    /// on the following delimiter, so we just try again.
+    fn next_block_cobs(&mut self) -> Result<Vec<f32>, SourceError> {
+        loop {
+            let mut bufreader = BufReader::new(self.port.take().expect("port taken twice"));
+            self.buf.clear();
+            bufreader.read_until(0, &mut self.buf)?;
+
+            let mut port = bufreader.into_inner();
+            port.write_all(b"p")?;
+            self.port = Some(port);
+
+            // A COBS-encoded payload never contains a zero byte, so the
+            // trailing one is always the frame delimiter, not data.
+            if self.buf.pop() != Some(0) {
+                continue;
+            }
+
+            let Ok(decoded) = cobs::decode_vec(&self.buf) else {
+                continue;
+            };
+            let Ok(block) = postcard::from_bytes::<CobsBlock>(&decoded) else {
+                continue;
+            };
+            if block.samples.len() != N {
+                continue;
+            }
+
+            self.sample_rate = block.sample_rate as f64;
+            let mean = block.samples.iter().map(|s| *s as usize).sum::<usize>() as f32 / N as f32;
+            return Ok(block.samples.iter().map(|s| *s as f32 - mean).collect());
+        }
+    }
+}
+
+impl SampleSource for SerialSource {
+    fn sample_rate(&self) -> f64 {
+        self.sample_rate
+    }
+
+    fn full_scale(&self) -> f32 {
+        SERIAL_FULL_SCALE
+    }
+
+    fn next_block(&mut self) -> Result<Vec<f32>, SourceError> {
+        match self.protocol {
+            Protocol::Text => self.next_block_text(),
+            Protocol::Cobs => self.next_block_cobs(),
+        }
+    }
+}
@@ -0,0 +1,57 @@
+//! A scrolling spectrogram: a fixed-height ring of recent FFT frames,
+//! rendered as a texture with frequency on one axis and time on the other.
+
+use std::collections::VecDeque;
+
+use crate::colormap::Colormap;
+
+/// How many rows of history the waterfall keeps.
+const HEIGHT: usize = 200;
+
+/// Ring buffer of recent per-bin dBFS rows, newest last.
+pub struct Waterfall {
+    rows: VecDeque<Vec<f32>>,
+}
+
+impl Default for Waterfall {
+    fn default() -> Self {
+        Waterfall {
+            rows: VecDeque::with_capacity(HEIGHT),
+        }
+    }
+}
+
+impl Waterfall {
+    /// Push a new row of per-bin dBFS values, dropping the oldest row once
+    /// the ring is full.
+    pub fn push(&mut self, row: Vec<f32>) {
+        if self.rows.len() == HEIGHT {
+            self.rows.pop_front();
+        }
+        self.rows.push_back(row);
+    }
+
+    /// Render the ring into an `egui::ColorImage`, `db_min..=db_max` mapped
+    /// across `colormap`. Each row becomes one line of pixels, oldest at
+    /// the top and newest at the bottom.
+    pub fn to_color_image(&self, colormap: Colormap, db_min: f32, db_max: f32) -> egui::ColorImage {
+        let width = self.rows.front().map(|r| r.len()).unwrap_or(1);
+        let height = HEIGHT;
+        let mut pixels = vec![egui::Color32::BLACK; width * height];
+
+        // Top-pad with black if we don't have a full ring yet
+        let pad = height - self.rows.len();
+        for (y, row) in self.rows.iter().enumerate() {
+            for (x, db) in row.iter().enumerate() {
+                let t = (db - db_min) / (db_max - db_min);
+                let [r, g, b] = colormap.sample(t);
+                pixels[(pad + y) * width + x] = egui::Color32::from_rgb(r, g, b);
+            }
+        }
+
+        egui::ColorImage {
+            size: [width, height],
+            pixels,
+        }
+    }
+}
@@ -0,0 +1,62 @@
+//! Window functions applied to the ADC samples before the FFT.
+//!
+//! Feeding the FFT raw rectangular-windowed samples causes any tone that
+//! doesn't land exactly on a bin center to smear into its neighbors
+//! (spectral leakage). Tapering the block with one of these windows first
+//! trades some main-lobe width for much lower leakage.
+
+use std::f32::consts::PI;
+
+/// Selectable window function applied to the sample block before the FFT.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Window {
+    #[default]
+    Hann,
+    Hamming,
+    Blackman,
+    Rectangular,
+}
+
+impl Window {
+    /// All selectable variants, in the order they should appear in the GUI.
+    pub const ALL: [Window; 4] = [
+        Window::Hann,
+        Window::Hamming,
+        Window::Blackman,
+        Window::Rectangular,
+    ];
+
+    /// Human readable label for the window picker.
+    pub fn label(self) -> &'static str {
+        match self {
+            Window::Hann => "Hann",
+            Window::Hamming => "Hamming",
+            Window::Blackman => "Blackman",
+            Window::Rectangular => "Rectangular",
+        }
+    }
+
+    /// Compute the `n`-point coefficient table for this window, along with
+    /// its coherent gain (the mean of the coefficients). The reader divides
+    /// the post-FFT magnitudes by this gain so a full-scale sine still
+    /// reads 0 dBFS regardless of which window is selected.
+    pub fn coefficients(self, n: usize) -> (Vec<f32>, f32) {
+        let w: Vec<f32> = match self {
+            Window::Rectangular => vec![1.; n],
+            Window::Hann => (0..n)
+                .map(|i| 0.5 * (1. - (2. * PI * i as f32 / (n - 1) as f32).cos()))
+                .collect(),
+            Window::Hamming => (0..n)
+                .map(|i| 0.54 - 0.46 * (2. * PI * i as f32 / (n - 1) as f32).cos())
+                .collect(),
+            Window::Blackman => (0..n)
+                .map(|i| {
+                    let x = 2. * PI * i as f32 / (n - 1) as f32;
+                    0.42 - 0.5 * x.cos() + 0.08 * (2. * x).cos()
+                })
+                .collect(),
+        };
+        let gain = w.iter().sum::<f32>() / n as f32;
+        (w, gain)
+    }
+}